@@ -2,6 +2,8 @@ use std::error;
 use std::fmt;
 use std::error::Error;
 
+use crate::dpf;
+
 /// Hold a set of bits that can be set, unset, and tested by index.
 /// Basically behave like an array of bits.
 #[derive(Debug)]
@@ -104,6 +106,51 @@ impl Bitmap {
     pub fn len(&self) -> usize {
         32 * self.items.len()
     }
+
+    /// Serialize to a little-endian byte stream, one u32 word at a time.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.items.len() * 4);
+        for item in &self.items {
+            out.extend_from_slice(&item.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reconstruct a `Bitmap` of `bit_count` bits from the bytes produced by [`to_bytes`].
+    pub fn from_bytes(bytes: &[u8], bit_count: usize) -> Result<Bitmap, Box<dyn Error>> {
+        let mut b = Bitmap::new(bit_count)?;
+        for (i, word) in bytes.chunks(4).enumerate() {
+            if i >= b.items.len() {
+                break;
+            }
+            let mut le = [0u8; 4];
+            le[..word.len()].copy_from_slice(word);
+            b.items[i] = u32::from_le_bytes(le);
+        }
+        Ok(b)
+    }
+
+    /// Answer a private-lookup DPF query: XOR together this server's bits at
+    /// every index the key's expansion marks, giving this server's share of
+    /// the revocation bit at the (hidden) queried index. The caller combines
+    /// this with the other server's share via `dpf::reconstruct`.
+    pub fn answer_dpf_query(&self, key: &dpf::DpfKey) -> dpf::Share {
+        let expansion = dpf::eval_all(key);
+        let mut share = false;
+        // `expansion`'s domain is `2^n_bits`, which `gen`'s caller only has
+        // to make *at least* `self.len()` (see `dpf::gen`'s precondition);
+        // indices at or past `self.len()` don't correspond to a real bit, so
+        // skip them instead of indexing out of bounds.
+        for (i, &marked) in expansion.iter().enumerate() {
+            if i >= self.len() {
+                break;
+            }
+            if marked && self.get_bit(i) {
+                share = !share;
+            }
+        }
+        share
+    }
 }
 
 #[cfg(test)]
@@ -141,6 +188,35 @@ mod tests {
         assert_eq!(n, 1u8 << 5);
     }
 
+    #[test]
+    fn answer_dpf_query_handles_a_domain_larger_than_the_bitmap() {
+        // `Bitmap::new` only rounds up to a multiple of 32, not to a power
+        // of two, so a realistic bitmap length (here, 1500 -> 1504 bits)
+        // essentially never matches `2^n_bits` for the smallest `n_bits`
+        // that covers it (11 -> 2048). Indices in that gap must be treated
+        // as "not set" instead of indexing past the bitmap's storage.
+        let alpha = 777;
+        let mut b = Bitmap::new(1500).unwrap();
+        b.set_bit(alpha);
+
+        let (key0, key1) = dpf::gen(alpha, 11);
+        let share0 = b.answer_dpf_query(&key0);
+        let share1 = b.answer_dpf_query(&key1);
+        assert_eq!(dpf::reconstruct(share0, share1), true);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut b = Bitmap::new(256).unwrap();
+        b.set_bit(3);
+        b.set_bit(200);
+        let bytes = b.to_bytes();
+        let b2 = Bitmap::from_bytes(&bytes, 256).unwrap();
+        assert_eq!(b2.get_bit(3), true);
+        assert_eq!(b2.get_bit(200), true);
+        assert_eq!(b2.get_bit(4), false);
+    }
+
     #[test]
     #[cfg(__notyet__)]
     fn load_bad_revlist_not_json() {
@@ -0,0 +1,256 @@
+//! Bloom filter cascade: a compact, constant-false-positive alternative to the
+//! sparse Merkle tree for representing a revocation set (see `bitmap::Bitmap`
+//! and `build_tree_from_bitmap` in `lib.rs`). A cascade is much smaller than
+//! the Poseidon `Db` once the set of revoked indices is sparse, at the cost
+//! of revealing only membership, not a cryptographic accumulator proof.
+
+use crate::bitmap::Bitmap;
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryInto;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+
+/// A single Bloom filter level of the cascade, backed by the same bit storage
+/// used for revocation lists elsewhere in this crate.
+struct BloomFilter {
+    bits: Bitmap,
+    size: usize,
+    k: usize,
+}
+
+impl BloomFilter {
+    fn new(expected_items: usize, fp_rate: f64) -> Result<BloomFilter, Box<dyn Error>> {
+        let n = expected_items.max(1) as f64;
+        let size = ((-n * fp_rate.ln()) / (2f64.ln().powi(2)))
+            .ceil()
+            .max(1.0) as usize;
+        let k = ((size as f64 / n) * 2f64.ln()).round().max(1.0) as usize;
+        Ok(BloomFilter { bits: Bitmap::new(size)?, size, k })
+    }
+
+    fn hash_with_seed(item: usize, seed: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        item.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Double hashing (Kirsch-Mitzenmacher): derive k indices from two hashes
+    // instead of running k independent hash functions.
+    fn indices(&self, item: usize) -> Vec<usize> {
+        let h1 = BloomFilter::hash_with_seed(item, 0);
+        let h2 = BloomFilter::hash_with_seed(item, 1);
+        (0..self.k)
+            .map(|i| (h1.wrapping_add((i as u64).wrapping_mul(h2)) % self.size as u64) as usize)
+            .collect()
+    }
+
+    fn insert(&mut self, item: usize) {
+        for idx in self.indices(item) {
+            self.bits.set_bit(idx);
+        }
+    }
+
+    fn contains(&self, item: usize) -> bool {
+        self.indices(item).into_iter().all(|idx| self.bits.get_bit(idx))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let bytes = self.bits.to_bytes();
+        let mut out = Vec::with_capacity(12 + bytes.len());
+        out.extend_from_slice(&(self.size as u32).to_le_bytes());
+        out.extend_from_slice(&(self.k as u32).to_le_bytes());
+        out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        out.extend_from_slice(&bytes);
+        out
+    }
+
+    fn deserialize(bytes: &[u8]) -> Result<(BloomFilter, &[u8]), Box<dyn Error>> {
+        if bytes.len() < 12 {
+            return Err(Box::from("truncated bloom filter header"));
+        }
+        let size = u32::from_le_bytes(bytes[0..4].try_into()?) as usize;
+        let k = u32::from_le_bytes(bytes[4..8].try_into()?) as usize;
+        let byte_len = u32::from_le_bytes(bytes[8..12].try_into()?) as usize;
+        let body = &bytes[12..];
+        if body.len() < byte_len {
+            return Err(Box::from("truncated bloom filter body"));
+        }
+        let bits = Bitmap::from_bytes(&body[..byte_len], size)?;
+        Ok((BloomFilter { bits, size, k }, &body[byte_len..]))
+    }
+}
+
+/// A cascade of Bloom filters that, taken together, answer set-membership
+/// queries for the revoked indices in a `Bitmap` exactly, at a fraction of
+/// the size of a sparse Merkle tree over the same indices.
+///
+/// Level 0 is built from the revoked set R; level 1 is built from the
+/// indices of the non-revoked set S that falsely matched level 0; level 2
+/// is built from the indices of R that falsely matched level 1; and so on
+/// until a level produces no false positives.
+pub struct Cascade {
+    levels: Vec<BloomFilter>,
+}
+
+/// Hard cap on the number of levels `from_bitmap` will build. A well-chosen
+/// `fp_rate` makes the false-positive chase converge in just a few levels in
+/// expectation, but nothing stops attacker-influenced revocation data from
+/// trying to keep it going; this bounds the cost of that instead of looping
+/// unbounded.
+const MAX_LEVELS: usize = 32;
+
+impl Cascade {
+    pub fn from_bitmap(b: &Bitmap, fp_rate: f64) -> Result<Cascade, Box<dyn Error>> {
+        Cascade::from_bitmap_capped(b, fp_rate, MAX_LEVELS)
+    }
+
+    // Split out from `from_bitmap` so tests can exercise the cap with a
+    // small `max_levels` instead of needing input that takes 32 real levels
+    // to trip it.
+    fn from_bitmap_capped(b: &Bitmap, fp_rate: f64, max_levels: usize) -> Result<Cascade, Box<dyn Error>> {
+        let mut revoked = Vec::new();
+        let mut not_revoked = Vec::new();
+        for i in 0..b.len() {
+            if b.get_bit(i) {
+                revoked.push(i);
+            } else {
+                not_revoked.push(i);
+            }
+        }
+
+        let mut levels = Vec::new();
+        // `source` is the set the current level is built from; `victims` is
+        // the complementary set it's tested against to find false positives.
+        let mut source = revoked;
+        let mut victims = not_revoked;
+        loop {
+            let mut filter = BloomFilter::new(source.len(), fp_rate)
+                .expect("bloom filter sizing should never fail for a non-negative item count");
+            for &item in &source {
+                filter.insert(item);
+            }
+            let false_positives: Vec<usize> = victims
+                .iter()
+                .cloned()
+                .filter(|&item| filter.contains(item))
+                .collect();
+            levels.push(filter);
+            if false_positives.is_empty() {
+                break;
+            }
+            if levels.len() >= max_levels {
+                return Err(Box::from(format!(
+                    "bloom cascade false-positive chase didn't converge within {} levels",
+                    max_levels
+                )));
+            }
+            victims = source;
+            source = false_positives;
+        }
+        Ok(Cascade { levels })
+    }
+
+    /// Test whether `index` is revoked. Walk the levels from 0; the first
+    /// level that does *not* match decides the answer. Level 0 is built
+    /// straight from the revoked set, and Bloom filters never produce false
+    /// negatives, so a miss there is a guaranteed "not revoked"; a miss at
+    /// the next level (built from level 0's false positives) is therefore a
+    /// guaranteed "revoked", and so on, alternating. Matching every level
+    /// means the index is in the set the last level was built from: revoked
+    /// if that level has an even index (built from revoked indices), not
+    /// revoked if odd.
+    pub fn contains(&self, index: usize) -> bool {
+        for (level, filter) in self.levels.iter().enumerate() {
+            if !filter.contains(index) {
+                return level % 2 != 0;
+            }
+        }
+        self.levels.len() % 2 == 1
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        for filter in &self.levels {
+            out.extend_from_slice(&filter.serialize());
+        }
+        out
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Cascade, Box<dyn Error>> {
+        if bytes.len() < 4 {
+            return Err(Box::from("truncated cascade header"));
+        }
+        let level_count = u32::from_le_bytes(bytes[0..4].try_into()?) as usize;
+        let mut rest = &bytes[4..];
+        let mut levels = Vec::with_capacity(level_count);
+        for _ in 0..level_count {
+            let (filter, remainder) = BloomFilter::deserialize(rest)?;
+            levels.push(filter);
+            rest = remainder;
+        }
+        Ok(Cascade { levels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_bitmap(revoked: &[usize], size: usize) -> Bitmap {
+        let mut b = Bitmap::new(size).unwrap();
+        for &i in revoked {
+            b.set_bit(i);
+        }
+        b
+    }
+
+    #[test]
+    fn matches_revoked_and_not_revoked() {
+        let revoked = [3usize, 40, 900, 4095];
+        let b = make_bitmap(&revoked, 4096);
+        let cascade = Cascade::from_bitmap(&b, 0.01).unwrap();
+        for &i in &revoked {
+            assert_eq!(cascade.contains(i), true);
+        }
+        for i in [0usize, 1, 2, 41, 4094] {
+            assert_eq!(cascade.contains(i), b.get_bit(i));
+        }
+    }
+
+    #[test]
+    fn empty_revocation_set() {
+        let b = Bitmap::new(1024).unwrap();
+        let cascade = Cascade::from_bitmap(&b, 0.01).unwrap();
+        for i in [0usize, 1, 512, 1023] {
+            assert_eq!(cascade.contains(i), false);
+        }
+    }
+
+    #[test]
+    fn errors_when_cascade_cant_converge_within_the_cap() {
+        let revoked: Vec<usize> = (0..10).collect();
+        let b = make_bitmap(&revoked, 64);
+        // fp_rate 0.5 makes a hopelessly weak filter, so the remaining 54
+        // indices are virtually certain to produce at least one false
+        // positive, which is enough to blow a 1-level cap.
+        assert!(Cascade::from_bitmap_capped(&b, 0.5, 1).is_err());
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let b = make_bitmap(&[7, 70, 700], 2048);
+        let cascade = Cascade::from_bitmap(&b, 0.01).unwrap();
+        let bytes = cascade.serialize();
+        let restored = Cascade::deserialize(&bytes).unwrap();
+        assert_eq!(restored.level_count(), cascade.level_count());
+        for i in [7usize, 70, 700, 8, 71] {
+            assert_eq!(restored.contains(i), cascade.contains(i));
+        }
+    }
+}
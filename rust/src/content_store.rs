@@ -0,0 +1,243 @@
+//! Content-defined chunking (FastCDC) plus content-addressed dedup.
+//!
+//! `Db` (`InMemoryHashDb` from `bulletproofs_amcl`) is a foreign type, so we
+//! can't add a dedicated `save`/`load` to it directly. Instead this module
+//! chunks and dedups whatever byte stream `Db::save` already produced on
+//! disk, which is where the win is anyway: sparse trees serialize to huge
+//! runs of identical subtrees (the all-zeros node and its ancestors), and
+//! those runs turn into identical chunks that only need to be stored once.
+//! See `experiment` in `lib.rs` for the comparison against plain zip
+//! compression.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Content address for a chunk. A general-purpose 64-bit hash (the original
+/// version of this module used `DefaultHasher`) isn't collision-resistant
+/// enough to key a content-addressed store by: a collision would make
+/// `save` silently drop one of the two chunks' bytes, with `load`
+/// reconstructing the wrong data and nothing anywhere raising an error.
+/// BLAKE3 is already a dependency (see `verified_store`), so use it here too.
+pub type ChunkHash = [u8; 32];
+
+/// Below this many bytes into a chunk, never cut (even if the rolling
+/// fingerprint would otherwise match).
+pub const MIN_SIZE: usize = 2 * 1024;
+/// At this many bytes into a chunk, force a cut regardless of the
+/// fingerprint, so a pathological input can't produce unbounded chunks.
+pub const MAX_SIZE: usize = 64 * 1024;
+/// The chunk size normalized chunking aims for on average.
+pub const TARGET_SIZE: usize = 8 * 1024;
+
+// Below TARGET_SIZE, require more zero bits in the fingerprint (stricter,
+// less likely to match) so chunks are discouraged from cutting early. Past
+// TARGET_SIZE, require fewer (looser, more likely to match) so the chunk
+// is encouraged to cut soon. This is FastCDC's "normalized chunking".
+const MASK_SMALL: u64 = (1u64 << 15) - 1;
+const MASK_LARGE: u64 = (1u64 << 11) - 1;
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut seed: u64 = 0x9E3779B97F4A7C15;
+    let mut i = 0;
+    while i < 256 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        table[i] = seed;
+        i += 1;
+    }
+    table
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+/// Find the byte offsets (exclusive ends) of each content-defined chunk in
+/// `data`, using a rolling gear-hash fingerprint with normalized chunking.
+fn chunk_boundaries(data: &[u8]) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let remaining = data.len() - start;
+        if remaining <= MIN_SIZE {
+            boundaries.push(data.len());
+            break;
+        }
+
+        let mut fp: u64 = 0;
+        let mut offset = MIN_SIZE;
+        for &b in &data[start..start + offset] {
+            fp = (fp << 1).wrapping_add(GEAR[b as usize]);
+        }
+
+        let mut cut = remaining;
+        while offset < remaining {
+            let b = data[start + offset];
+            fp = (fp << 1).wrapping_add(GEAR[b as usize]);
+            offset += 1;
+            let mask = if offset < TARGET_SIZE { MASK_SMALL } else { MASK_LARGE };
+            if (fp & mask) == 0 || offset >= MAX_SIZE {
+                cut = offset;
+                break;
+            }
+        }
+        start += cut;
+        boundaries.push(start);
+    }
+    boundaries
+}
+
+fn hash_chunk(chunk: &[u8]) -> ChunkHash {
+    *blake3::hash(chunk).as_bytes()
+}
+
+/// Size/dedup stats for a chunked byte stream, reported alongside the
+/// existing zip compression figures in `experiment`.
+pub struct ChunkStats {
+    pub chunk_count: usize,
+    pub unique_chunk_count: usize,
+    pub total_bytes: usize,
+    pub unique_bytes: usize,
+}
+
+impl ChunkStats {
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            1.0 - (self.unique_bytes as f64 / self.total_bytes as f64)
+        }
+    }
+}
+
+fn build_chunks(data: &[u8]) -> (Vec<ChunkHash>, HashMap<ChunkHash, &[u8]>) {
+    let mut ordered_hashes = Vec::new();
+    let mut unique = HashMap::new();
+    let mut start = 0;
+    for end in chunk_boundaries(data) {
+        let slice = &data[start..end];
+        let hash = hash_chunk(slice);
+        ordered_hashes.push(hash);
+        unique.entry(hash).or_insert(slice);
+        start = end;
+    }
+    (ordered_hashes, unique)
+}
+
+/// Chunk `data`, dedup identical chunks, and write the chunk table plus the
+/// ordered list of chunk hashes to `path`. Returns size/dedup stats.
+pub fn save(data: &[u8], path: &Path) -> io::Result<ChunkStats> {
+    let (ordered_hashes, unique) = build_chunks(data);
+
+    let mut out = File::create(path)?;
+    out.write_all(&(ordered_hashes.len() as u32).to_le_bytes())?;
+    out.write_all(&(unique.len() as u32).to_le_bytes())?;
+    let mut unique_bytes = 0;
+    for (hash, chunk) in &unique {
+        out.write_all(hash)?;
+        out.write_all(&(chunk.len() as u32).to_le_bytes())?;
+        out.write_all(chunk)?;
+        unique_bytes += chunk.len();
+    }
+    for hash in &ordered_hashes {
+        out.write_all(hash)?;
+    }
+
+    Ok(ChunkStats {
+        chunk_count: ordered_hashes.len(),
+        unique_chunk_count: unique.len(),
+        total_bytes: data.len(),
+        unique_bytes,
+    })
+}
+
+/// Reassemble the byte stream previously written by `save`.
+pub fn load(path: &Path) -> io::Result<Vec<u8>> {
+    let mut f = File::open(path)?;
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf)?;
+
+    let mut pos = 0;
+    let read_u32 = |buf: &[u8], pos: &mut usize| -> u32 {
+        let v = u32::from_le_bytes(buf[*pos..*pos + 4].try_into().unwrap());
+        *pos += 4;
+        v
+    };
+    let read_hash = |buf: &[u8], pos: &mut usize| -> ChunkHash {
+        let v: ChunkHash = buf[*pos..*pos + 32].try_into().unwrap();
+        *pos += 32;
+        v
+    };
+
+    let chunk_count = read_u32(&buf, &mut pos) as usize;
+    let unique_count = read_u32(&buf, &mut pos) as usize;
+
+    let mut table = HashMap::with_capacity(unique_count);
+    for _ in 0..unique_count {
+        let hash = read_hash(&buf, &mut pos);
+        let len = read_u32(&buf, &mut pos) as usize;
+        let bytes = buf[pos..pos + len].to_vec();
+        pos += len;
+        table.insert(hash, bytes);
+    }
+
+    let mut out = Vec::new();
+    for _ in 0..chunk_count {
+        let hash = read_hash(&buf, &mut pos);
+        let chunk = table.get(&hash).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "chunk store references an unknown hash")
+        })?;
+        out.extend_from_slice(chunk);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boundaries_cover_whole_input() {
+        let data = vec![7u8; MIN_SIZE * 5 + 37];
+        let boundaries = chunk_boundaries(&data);
+        assert_eq!(*boundaries.last().unwrap(), data.len());
+        let mut prev = 0;
+        for &b in &boundaries {
+            assert!(b > prev);
+            assert!(b - prev <= MAX_SIZE);
+            prev = b;
+        }
+    }
+
+    #[test]
+    fn repeated_blocks_dedup() {
+        // Long enough that a uniform byte stream forces several MAX_SIZE
+        // chunks in a row (the content never gives the rolling fingerprint a
+        // reason to cut early), so dedup has real duplicates to find
+        // regardless of where exactly the natural cut points fall.
+        let data = vec![42u8; MAX_SIZE * 6];
+        let (ordered, unique) = build_chunks(&data);
+        assert!(ordered.len() >= unique.len());
+        assert!(unique.len() < ordered.len());
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut data = Vec::new();
+        for i in 0..200_000u32 {
+            data.push((i % 251) as u8);
+        }
+        let dir = std::env::temp_dir();
+        let path = dir.join("content_store_round_trip_test.bin");
+        let stats = save(&data, &path).unwrap();
+        assert_eq!(stats.total_bytes, data.len());
+        assert!(stats.unique_chunk_count <= stats.chunk_count);
+        let reloaded = load(&path).unwrap();
+        assert_eq!(reloaded, data);
+        std::fs::remove_file(&path).ok();
+    }
+}
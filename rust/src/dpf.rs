@@ -0,0 +1,210 @@
+//! Two-server distributed point function (DPF) for private revocation lookup.
+//!
+//! A credential holder who wants to learn whether index `i` is revoked in a
+//! `bitmap::Bitmap` held by two non-colluding servers, without revealing `i`
+//! to either server, asks each server to evaluate a DPF key against its copy
+//! of the bitmap and XORs the two single-bit answers back together. Neither
+//! key alone reveals the queried index `alpha`: this is the standard
+//! GGM-tree construction (Boyle-Gilboa-Ishai), where each key is a root seed
+//! plus one correction word per level of a binary tree of depth `n_bits`
+//! (i.e. `ceil(log2(N))` for a domain of `N` points).
+//!
+//! The tree-expanding PRG (`prg`/`hash_branch`) is BLAKE3 keyed by `(seed,
+//! branch)`, not `std::collections::hash_map::DefaultHasher` as an earlier
+//! version of this module used: `DefaultHasher` is explicitly documented by
+//! the standard library as neither cryptographically secure nor stable
+//! across Rust releases, and privacy here depends entirely on the PRG being
+//! indistinguishable from random. BLAKE3 is already a dependency (see
+//! `content_store`/`verified_store`), so reuse it here too.
+
+type Seed = u64;
+
+/// One correction word, applied at a single tree level by both parties.
+struct CorrectionWord {
+    seed: Seed,
+    bit_l: bool,
+    bit_r: bool,
+}
+
+/// One party's share of a DPF for some point function f(x) = 1 iff x == alpha.
+pub struct DpfKey {
+    party: u8,
+    seed: Seed,
+    correction_words: Vec<CorrectionWord>,
+    output_correction: bool,
+}
+
+/// A server's single-bit share of a queried revocation bit. The client
+/// recovers the real answer via `reconstruct`.
+pub type Share = bool;
+
+// A PRG: expand a seed into two child seeds and two control bits. The low
+// bit of each hash output doubles as that branch's control bit, the rest of
+// the bits (with the low bit cleared) become the child seed.
+fn prg(seed: Seed) -> (Seed, bool, Seed, bool) {
+    let l = hash_branch(seed, 0);
+    let r = hash_branch(seed, 1);
+    (l & !1, l & 1 == 1, r & !1, r & 1 == 1)
+}
+
+fn hash_branch(seed: Seed, branch: u8) -> u64 {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&seed.to_le_bytes());
+    hasher.update(&[branch]);
+    let mut out = [0u8; 8];
+    hasher.finalize_xof().fill(&mut out);
+    u64::from_le_bytes(out)
+}
+
+fn random_seed() -> Seed {
+    use rand::Rng;
+    rand::thread_rng().gen()
+}
+
+/// Generate a pair of DPF keys for the point function that's 1 only at
+/// `alpha`, over a domain of `n_bits` bits (i.e. `2^n_bits` points).
+///
+/// `n_bits` must be large enough that `2^n_bits` covers every index the
+/// resulting keys will be evaluated against (e.g. every index of the
+/// `bitmap::Bitmap` they'll be passed to via `answer_dpf_query`) — `Bitmap`
+/// only rounds its length up to a multiple of 32, not to a power of two, so
+/// callers need to round `n_bits` up themselves.
+pub fn gen(alpha: usize, n_bits: usize) -> (DpfKey, DpfKey) {
+    assert!(alpha < (1usize << n_bits), "alpha {} is outside the 2^{} domain", alpha, n_bits);
+    let orig_seed0 = random_seed();
+    let orig_seed1 = random_seed();
+    // seed0/seed1/t0/t1 track the path that matches alpha as we descend,
+    // level by level, so we can compute each level's correction word.
+    let mut seed0 = orig_seed0;
+    let mut seed1 = orig_seed1;
+    let mut t0 = false;
+    let mut t1 = true;
+    let mut correction_words = Vec::with_capacity(n_bits);
+
+    for level in 0..n_bits {
+        let alpha_bit = (alpha >> (n_bits - 1 - level)) & 1 == 1;
+        let (s0_l, t0_l, s0_r, t0_r) = prg(seed0);
+        let (s1_l, t1_l, s1_r, t1_r) = prg(seed1);
+
+        let (lose_s0, lose_s1) = if alpha_bit { (s0_l, s1_l) } else { (s0_r, s1_r) };
+        let cw_seed = lose_s0 ^ lose_s1;
+        let cw_l = t0_l ^ t1_l ^ alpha_bit ^ true;
+        let cw_r = t0_r ^ t1_r ^ alpha_bit;
+
+        let (keep_s0, keep_t0, keep_s1, keep_t1, cw_keep) = if alpha_bit {
+            (s0_r, t0_r, s1_r, t1_r, cw_r)
+        } else {
+            (s0_l, t0_l, s1_l, t1_l, cw_l)
+        };
+
+        seed0 = keep_s0 ^ if t0 { cw_seed } else { 0 };
+        seed1 = keep_s1 ^ if t1 { cw_seed } else { 0 };
+        t0 = keep_t0 ^ (t0 && cw_keep);
+        t1 = keep_t1 ^ (t1 && cw_keep);
+
+        correction_words.push(CorrectionWord { seed: cw_seed, bit_l: cw_l, bit_r: cw_r });
+    }
+
+    // t0 XOR t1 == 1 is an invariant maintained at every level, so this
+    // correction word makes the two parties' leaf output bits XOR to 1
+    // exactly at the leaf reached by following alpha's path.
+    let leaf_bit0 = seed0 & 1 == 1;
+    let leaf_bit1 = seed1 & 1 == 1;
+    let output_correction = true ^ leaf_bit0 ^ leaf_bit1;
+
+    (
+        DpfKey { party: 0, seed: orig_seed0, correction_words: correction_words_clone(&correction_words), output_correction },
+        DpfKey { party: 1, seed: orig_seed1, correction_words, output_correction },
+    )
+}
+
+fn correction_words_clone(words: &[CorrectionWord]) -> Vec<CorrectionWord> {
+    words.iter().map(|w| CorrectionWord { seed: w.seed, bit_l: w.bit_l, bit_r: w.bit_r }).collect()
+}
+
+/// Expand a key across the full `2^n_bits` domain, giving this party's share
+/// of the indicator vector for `alpha`. XORing the two parties' expansions
+/// together yields a vector that is 1 at `alpha` and 0 everywhere else.
+pub fn eval_all(key: &DpfKey) -> Vec<bool> {
+    let mut seeds = vec![key.seed];
+    let mut bits = vec![key.party == 1];
+    for cw in &key.correction_words {
+        let mut next_seeds = Vec::with_capacity(seeds.len() * 2);
+        let mut next_bits = Vec::with_capacity(seeds.len() * 2);
+        for (&seed, &t) in seeds.iter().zip(bits.iter()) {
+            let (s_l, t_l, s_r, t_r) = prg(seed);
+            next_seeds.push(s_l ^ if t { cw.seed } else { 0 });
+            next_bits.push(t_l ^ (t && cw.bit_l));
+            next_seeds.push(s_r ^ if t { cw.seed } else { 0 });
+            next_bits.push(t_r ^ (t && cw.bit_r));
+        }
+        seeds = next_seeds;
+        bits = next_bits;
+    }
+    seeds.iter().zip(bits.iter())
+        .map(|(&s, &t)| (s & 1 == 1) ^ (t && key.output_correction))
+        .collect()
+}
+
+/// Recover the real revocation bit from the two servers' shares.
+pub fn reconstruct(share0: Share, share1: Share) -> bool {
+    share0 ^ share1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::Bitmap;
+
+    #[test]
+    fn recovers_revoked_bit() {
+        let n_bits = 10;
+        let capacity = 1usize << n_bits;
+        let alpha = 777;
+        let mut b = Bitmap::new(capacity).unwrap();
+        b.set_bit(alpha);
+
+        let (key0, key1) = gen(alpha, n_bits);
+        let share0 = b.answer_dpf_query(&key0);
+        let share1 = b.answer_dpf_query(&key1);
+        assert_eq!(reconstruct(share0, share1), true);
+    }
+
+    #[test]
+    fn recovers_non_revoked_bit() {
+        let n_bits = 10;
+        let capacity = 1usize << n_bits;
+        let alpha = 42;
+        let b = Bitmap::new(capacity).unwrap();
+
+        let (key0, key1) = gen(alpha, n_bits);
+        let share0 = b.answer_dpf_query(&key0);
+        let share1 = b.answer_dpf_query(&key1);
+        assert_eq!(reconstruct(share0, share1), false);
+    }
+
+    #[test]
+    fn single_key_does_not_reveal_alpha() {
+        // A single party's expansion should look like noise, not a one-hot
+        // vector pointing at alpha - roughly half the bits should be set.
+        let n_bits = 8;
+        let capacity = 1usize << n_bits;
+        let (key0, _key1) = gen(123, n_bits);
+        let expansion = eval_all(&key0);
+        let set_count = expansion.iter().filter(|&&b| b).count();
+        assert!(set_count > capacity / 8 && set_count < capacity * 7 / 8);
+    }
+
+    #[test]
+    fn expansions_xor_to_indicator_vector() {
+        let n_bits = 8;
+        let capacity = 1usize << n_bits;
+        let alpha = 200;
+        let (key0, key1) = gen(alpha, n_bits);
+        let e0 = eval_all(&key0);
+        let e1 = eval_all(&key1);
+        for i in 0..capacity {
+            assert_eq!(e0[i] ^ e1[i], i == alpha);
+        }
+    }
+}
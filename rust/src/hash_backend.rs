@@ -0,0 +1,183 @@
+//! Pluggable backend for the 8-ary Merkle tree hash used by `experiment` and
+//! `build_tree_from_bitmap`, so callers can pick a hash at runtime instead of
+//! always paying for Poseidon's SNARK-friendly (but comparatively slow)
+//! arithmetic.
+
+use bulletproofs_amcl::errors::R1CSError;
+use bulletproofs_amcl::r1cs::gadgets::helper_constraints::poseidon::{PoseidonParams, SboxType};
+use bulletproofs_amcl::r1cs::gadgets::merkle_tree_hash::{Arity8MerkleTreeHash, PoseidonHash8};
+use amcl_wrapper::constants::FieldElement_SIZE;
+use amcl_wrapper::field_elem::FieldElement;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Which 8-ary tree hash `experiment` should build/fill against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashKind {
+    Poseidon,
+    Blake3,
+}
+
+/// `Arity8MerkleTreeHash` plus an optional per-level domain-separation hook
+/// and a hook for hashing a whole level's worth of sibling groups at once.
+/// Poseidon doesn't need either (it gets its own default, sequential,
+/// no-op impls below); `Blake3Hash8` uses both, since unlike Poseidon's
+/// R1CS-gadget wiring, one BLAKE3 call has no serial dependency on another
+/// and the groups in a level can be hashed in parallel.
+pub trait Arity8MerkleTreeHashExt: Arity8MerkleTreeHash {
+    fn set_level(&self, _level: usize) {}
+
+    /// Hash every 8-child group in `groups`, in order. The default just
+    /// calls `hash` once per group.
+    fn hash_level(&self, groups: Vec<Vec<FieldElement>>) -> Result<Vec<FieldElement>, R1CSError> {
+        groups.into_iter().map(|g| self.hash(g)).collect()
+    }
+}
+
+impl Arity8MerkleTreeHashExt for PoseidonHash8<'_> {}
+
+/// An 8-ary Merkle tree hash backed by BLAKE3 instead of Poseidon, for
+/// deployments that don't need SNARK-friendly hashing and want the fastest
+/// possible fill/build times.
+pub struct Blake3Hash8 {
+    // Atomic rather than `Cell` so `&Blake3Hash8` is `Sync` and `hash_level`
+    // can share `self` across the threads it fans a level's hashing out to.
+    level: AtomicUsize,
+}
+
+impl Blake3Hash8 {
+    pub fn new() -> Blake3Hash8 {
+        Blake3Hash8 { level: AtomicUsize::new(0) }
+    }
+}
+
+impl Arity8MerkleTreeHash for Blake3Hash8 {
+    fn hash(&self, children: Vec<FieldElement>) -> Result<FieldElement, R1CSError> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&(self.level.load(Ordering::Relaxed) as u64).to_le_bytes());
+        for child in &children {
+            hasher.update(&child.to_bytes());
+        }
+        // BLAKE3's regular `finalize()` only emits a 32-byte digest, but
+        // `FieldElement::from_bytes` requires exactly `FieldElement_SIZE`
+        // bytes (48 for the `bls381` feature `make_hash_params` is written
+        // for) and errors on anything else. Use the XOF to emit exactly the
+        // width `FieldElement` wants instead of assuming 32 bytes fits.
+        let mut wide = vec![0u8; FieldElement_SIZE];
+        hasher.finalize_xof().fill(&mut wide);
+        Ok(FieldElement::from_bytes(&wide).unwrap())
+    }
+}
+
+impl Arity8MerkleTreeHashExt for Blake3Hash8 {
+    fn set_level(&self, level: usize) {
+        self.level.store(level, Ordering::Relaxed);
+    }
+
+    // Unlike Poseidon's R1CS-gadget wiring, one BLAKE3 call has no serial
+    // dependency on another, so a level's sibling groups can be fanned out
+    // across threads instead of hashed one at a time.
+    fn hash_level(&self, groups: Vec<Vec<FieldElement>>) -> Result<Vec<FieldElement>, R1CSError> {
+        if groups.len() < 2 {
+            return groups.into_iter().map(|g| self.hash(g)).collect();
+        }
+        let thread_count = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(groups.len());
+        let chunk_size = (groups.len() + thread_count - 1) / thread_count;
+
+        let mut results: Vec<Option<Result<FieldElement, R1CSError>>> =
+            (0..groups.len()).map(|_| None).collect();
+        std::thread::scope(|scope| {
+            let mut handles = Vec::new();
+            for (chunk_index, chunk) in groups.chunks(chunk_size).enumerate() {
+                handles.push((chunk_index, scope.spawn(move || {
+                    chunk.iter().map(|g| self.hash(g.clone())).collect::<Vec<_>>()
+                })));
+            }
+            for (chunk_index, handle) in handles {
+                let start = chunk_index * chunk_size;
+                for (offset, outcome) in handle.join().unwrap().into_iter().enumerate() {
+                    results[start + offset] = Some(outcome);
+                }
+            }
+        });
+
+        results.into_iter().map(|r| r.unwrap()).collect()
+    }
+}
+
+// Let a boxed backend stand in for a concrete, Sized one (e.g. as the `H` in
+// `VanillaSparseMerkleTree8<'a, H>`), so `experiment` can pick a backend at
+// runtime with a single `match` in `make_hash_func` instead of threading a
+// type parameter through every caller.
+impl Arity8MerkleTreeHash for Box<dyn Arity8MerkleTreeHashExt + '_> {
+    fn hash(&self, children: Vec<FieldElement>) -> Result<FieldElement, R1CSError> {
+        (**self).hash(children)
+    }
+}
+
+impl Arity8MerkleTreeHashExt for Box<dyn Arity8MerkleTreeHashExt + '_> {
+    fn set_level(&self, level: usize) {
+        (**self).set_level(level)
+    }
+
+    fn hash_level(&self, groups: Vec<Vec<FieldElement>>) -> Result<Vec<FieldElement>, R1CSError> {
+        (**self).hash_level(groups)
+    }
+}
+
+pub fn make_hash_func<'a>(kind: HashKind, hash_params: &'a PoseidonParams) -> Box<dyn Arity8MerkleTreeHashExt + 'a> {
+    match kind {
+        HashKind::Poseidon => Box::new(PoseidonHash8 { params: hash_params, sbox: &SboxType::Quint }),
+        HashKind::Blake3 => Box::new(Blake3Hash8::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn children(values: &[u64]) -> Vec<FieldElement> {
+        values.iter().map(|&v| FieldElement::from(v)).collect()
+    }
+
+    #[test]
+    fn hash_does_not_panic_on_all_zero_children() {
+        let hash_func = Blake3Hash8::new();
+        hash_func.set_level(3);
+        let all_zeros = vec![FieldElement::zero(); 8];
+        hash_func.hash(all_zeros).unwrap();
+    }
+
+    #[test]
+    fn set_level_changes_the_hash() {
+        let hash_func = Blake3Hash8::new();
+        let group = children(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        hash_func.set_level(1);
+        let at_level_1 = hash_func.hash(group.clone()).unwrap();
+
+        hash_func.set_level(2);
+        let at_level_2 = hash_func.hash(group).unwrap();
+
+        assert_ne!(at_level_1, at_level_2);
+    }
+
+    #[test]
+    fn hash_level_matches_sequential_hash() {
+        let hash_func = Blake3Hash8::new();
+        hash_func.set_level(4);
+        let groups: Vec<Vec<FieldElement>> = (0..20)
+            .map(|g| children(&[g, g + 1, g + 2, g + 3, g + 4, g + 5, g + 6, g + 7]))
+            .collect();
+
+        let sequential: Vec<FieldElement> = groups
+            .iter()
+            .map(|g| hash_func.hash(g.clone()).unwrap())
+            .collect();
+        let parallel = hash_func.hash_level(groups).unwrap();
+
+        assert_eq!(sequential, parallel);
+    }
+}
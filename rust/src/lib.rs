@@ -1,45 +1,59 @@
-#![cfg(target_os="android")]
 #![allow(non_snake_case)]
 
-use std::ffi::{CString, CStr};
-use jni::JNIEnv;
-use jni::objects::{JObject, JString};
-use jni::sys::{jstring};
-
-#[no_mangle]
-pub unsafe extern fn Java_com_example_android_MainActivity_hello(env: JNIEnv, _: JObject, j_recipient: JString) -> jstring {
-    let recipient = CString::from(
-        CStr::from_ptr(
-            env.get_string(j_recipient).unwrap().as_ptr()
-        )
-    );
-
-    let output = env.new_string("Hello ".to_owned() + recipient.to_str().unwrap()).unwrap();
-    output.into_inner()
-
-    //use std::time::Instant;
-    //let start = Instant::now();
-    //experiment(4, 0.001);
-    //let summary = format!("Experiment ran in {} millis, ", start.elapsed().as_millis());
-    //let output = env.new_string(summary + recipient.to_str().unwrap()).unwrap();
-    //output.into_inner()
+// Only the JNI glue is Android-specific; everything else in this crate
+// (the merkle tree experiment, bitmaps, cascades, DPF, etc.) is plain Rust
+// and should build and test under `cargo test` on any host, not just an
+// Android target. Previously `#![cfg(target_os = "android")]` gated the
+// *whole* crate, which silently compiled every module (and its tests) out
+// of existence on a normal dev machine or CI runner.
+#[cfg(target_os = "android")]
+mod android {
+    use std::ffi::{CString, CStr};
+    use jni::JNIEnv;
+    use jni::objects::{JObject, JString};
+    use jni::sys::{jstring};
+
+    #[no_mangle]
+    pub unsafe extern fn Java_com_example_android_MainActivity_hello(env: JNIEnv, _: JObject, j_recipient: JString) -> jstring {
+        let recipient = CString::from(
+            CStr::from_ptr(
+                env.get_string(j_recipient).unwrap().as_ptr()
+            )
+        );
+
+        let output = env.new_string("Hello ".to_owned() + recipient.to_str().unwrap()).unwrap();
+        output.into_inner()
+
+        //use std::time::Instant;
+        //let start = Instant::now();
+        //experiment(4, 0.001, hash_backend::HashKind::Poseidon);
+        //let summary = format!("Experiment ran in {} millis, ", start.elapsed().as_millis());
+        //let output = env.new_string(summary + recipient.to_str().unwrap()).unwrap();
+        //output.into_inner()
+    }
 }
 
 use bulletproofs_amcl::{
     r1cs::gadgets::{
         helper_constraints::{
             sparse_merkle_tree_8_ary::{VanillaSparseMerkleTree8, DbVal8ary},
-            poseidon::{PoseidonParams, SboxType}
+            poseidon::PoseidonParams
         },
-        merkle_tree_hash::PoseidonHash8
     },
     utils::hash_db::InMemoryHashDb
 };
 use amcl_wrapper::field_elem::FieldElement;
 use std::io;
-use std::io::Write;
+use std::io::{Read, Write};
 
 mod bitmap;
+mod cascade;
+mod content_store;
+mod dpf;
+mod hash_backend;
+mod verified_store;
+
+use hash_backend::{Arity8MerkleTreeHashExt, HashKind};
 
 //extern crate jemalloc_ctl;
 //extern crate jemallocator;
@@ -79,7 +93,7 @@ pub fn memdump(milestone: &str, base_value: usize) -> usize {
     a
 }
 
-pub fn experiment(depth: usize, fill_ratio: f64) {
+pub fn experiment(depth: usize, fill_ratio: f64, hash_kind: HashKind) {
 
     let start_allocated = memdump("start of experiment", 0);
 
@@ -87,10 +101,7 @@ pub fn experiment(depth: usize, fill_ratio: f64) {
 
     let hash_params = make_hash_params();
 
-    let hash_func = PoseidonHash8 {
-        params: &hash_params,
-        sbox: &SboxType::Quint,
-    };
+    let hash_func = hash_backend::make_hash_func(hash_kind, &hash_params);
     let mut tree = VanillaSparseMerkleTree8::new(&hash_func, depth as usize, &mut db).unwrap();
 
     // How many leaf nodes does this tree have?
@@ -137,6 +148,19 @@ pub fn experiment(depth: usize, fill_ratio: f64) {
     println!("Saved hashdb ({} bytes) to compressed file {} ({} bytes; {:.1}% compression).",
              uncompressed_size, path.display(), compressed_size, compression_ratio * 100.0);
 
+    let mut raw = Vec::new();
+    fs::File::open(path).unwrap().read_to_end(&mut raw).ok();
+    let chunk_store_path = Path::new("/tmp/x.chunks");
+    let now = Instant::now();
+    let chunk_stats = content_store::save(&raw, chunk_store_path).unwrap();
+    let elapsed = now.elapsed().as_millis();
+    let chunk_store_size = fs::metadata(chunk_store_path).unwrap().len();
+    println!("Chunked {} bytes into {} chunks ({} unique, {:.1}% dedup) in {} millis -> {} bytes on disk (compressed zip was {} bytes).",
+             chunk_stats.total_bytes, chunk_stats.chunk_count, chunk_stats.unique_chunk_count,
+             chunk_stats.dedup_ratio() * 100.0, elapsed, chunk_store_size, compressed_size);
+    let reassembled = content_store::load(chunk_store_path).unwrap();
+    println!("Reassembled chunk store matches original bytes: {}.", reassembled == raw);
+
     let mut db2 = Db::new();
     let now = Instant::now();
     let root2 = db2.load(path).unwrap();
@@ -153,6 +177,18 @@ pub fn experiment(depth: usize, fill_ratio: f64) {
         println!("Roots changed.");
     }
 
+    let verified_path = Path::new("/tmp/x.verified");
+    let now = Instant::now();
+    let verified_root = verified_store::save_verified(&raw, verified_path).unwrap();
+    println!("Wrote verified-streaming container (root {}) in {} millis.",
+             verified_store::to_hex(&verified_root), now.elapsed().as_millis());
+    let now = Instant::now();
+    match verified_store::load_verified(verified_path, verified_root) {
+        Ok(restored) => println!("Verified-streaming integrity check passed ({} bytes) in {} millis.",
+                                  restored.len(), now.elapsed().as_millis()),
+        Err(e) => println!("Verified-streaming integrity check FAILED: {}", e),
+    }
+
     let now = Instant::now();
     let mut revlist = bitmap::Bitmap::new(capacity as usize).unwrap();
     for _ in 0..insert_count {
@@ -163,21 +199,24 @@ pub fn experiment(depth: usize, fill_ratio: f64) {
 
     let mut db = make_db();
     let hash_params = make_hash_params();
-    let hash_func = PoseidonHash8 {
-        params: &hash_params,
-        sbox: &SboxType::Quint,
-    };
+    let hash_func = hash_backend::make_hash_func(hash_kind, &hash_params);
     let now = Instant::now();
     let _tree2 = build_tree_from_bitmap(depth, &revlist, &hash_func, &mut db);
-    println!("Built tree from bitmap in {} millis.", now.elapsed().as_millis());
+    println!("Built tree from bitmap ({:?}) in {} millis.", hash_kind, now.elapsed().as_millis());
+
+    let now = Instant::now();
+    let cascade = cascade::Cascade::from_bitmap(&revlist, 0.001).unwrap();
+    let build_elapsed = now.elapsed().as_millis();
+    let cascade_bytes = cascade.serialize();
+    println!("Built {}-level bloom cascade ({} bytes) from bitmap in {} millis, vs {} nodes in the sparse tree db.",
+             cascade.level_count(), cascade_bytes.len(), build_elapsed, db.len());
 }
 
-fn build_tree_from_bitmap<'a>(
+fn build_tree_from_bitmap<'a, H: Arity8MerkleTreeHashExt>(
     depth: usize, b: &bitmap::Bitmap,
-    hash_func: &'a PoseidonHash8,
-    db: &mut Db) -> Tree<'a> {
+    hash_func: &'a H,
+    db: &mut Db) -> Tree<'a, H> {
 
-    use bulletproofs_amcl::r1cs::gadgets::merkle_tree_hash::Arity8MerkleTreeHash;
     use bulletproofs_amcl::utils::hash_db::HashDb;
 
     // Create a tree of the right depth. This will prepopulate the hash db with the hashes
@@ -189,8 +228,6 @@ fn build_tree_from_bitmap<'a>(
     //    FieldElement *;
     //    owned;
     //}
-    let capacity: usize = 8_u32.pow((depth - 1) as u32) as usize;
-    let mut children_at_prev_level: Vec<FieldElement> = Vec::with_capacity(capacity);
     // Create the value that represents 1 set bit.
     let one = FieldElement::one();
     // Create the most common set of children we're going to see.
@@ -206,7 +243,19 @@ fn build_tree_from_bitmap<'a>(
     ];
     // Figure out what the hash of all zeros is. We'll use this so often that it's
     // worth caching.
+    hash_func.set_level(depth);
     let hash_all_zeros = hash_func.hash(all_zeros.to_vec()).unwrap();
+
+    // Collect every non-all-zero leaf-level sibling group up front, then
+    // hash them all in one `hash_level` call -- this leaf level has
+    // `8^(depth-1)` groups, the overwhelming majority of a tree's total
+    // hashing work for any reasonably sparse bitmap, so it's the one most
+    // worth batching. All-zero groups skip hashing entirely and reuse the
+    // already-computed `hash_all_zeros`.
+    let group_count = (b.len() + 7) / 8;
+    let mut children_at_prev_level: Vec<FieldElement> = vec![hash_all_zeros.clone(); group_count];
+    let mut nonzero_groups: Vec<DbVal8ary> = Vec::new();
+    let mut nonzero_indices: Vec<usize> = Vec::new();
     let mut i = 0;
     loop {
         let next8 = b.get_byte_for_bit(i);
@@ -220,15 +269,8 @@ fn build_tree_from_bitmap<'a>(
                 }
                 sibling_index += 1;
             }
-            let this_hash = hash_func.hash(siblings.to_vec()).unwrap();
-            children_at_prev_level.push(this_hash.clone());
-            let this_hash_bytes = this_hash.to_bytes();
-            if !db.contains_key(&this_hash_bytes) {
-                db.insert(this_hash_bytes, siblings);
-            }
-        } else {
-            // Nothing to do. All vacant leaf nodes already exist in the sparse tree.
-            children_at_prev_level.push(hash_all_zeros.clone());
+            nonzero_indices.push(i / 8);
+            nonzero_groups.push(siblings);
         }
         i += 8;
         if i >= b.len() {
@@ -236,16 +278,52 @@ fn build_tree_from_bitmap<'a>(
         }
     }
 
+    let nonzero_hashes = hash_func
+        .hash_level(nonzero_groups.iter().map(|g| g.to_vec()).collect())
+        .unwrap();
+    for ((group_index, siblings), this_hash) in
+        nonzero_indices.into_iter().zip(nonzero_groups.into_iter()).zip(nonzero_hashes.into_iter())
+    {
+        children_at_prev_level[group_index] = this_hash.clone();
+        let this_hash_bytes = this_hash.to_bytes();
+        if !db.contains_key(&this_hash_bytes) {
+            db.insert(this_hash_bytes, siblings);
+        }
+    }
+
     for _level in (2..depth).rev() {
+        hash_func.set_level(_level);
         let children_at_this_level = children_at_prev_level;
-        children_at_prev_level = Vec::new();
+
+        // Collect every sibling group's starting index at this level up
+        // front (same order as the old hand-rolled descending loop), then
+        // hash them all in one `hash_level` call. Backends with no serial
+        // dependency between groups (BLAKE3) can fan that out across
+        // threads instead of hashing one group at a time like Poseidon has
+        // to. Keep only the (cheap) starting indices around rather than a
+        // second copy of the groups themselves -- `children_at_this_level`
+        // is still here to re-slice from once we need a group's siblings.
+        let mut starts = Vec::new();
         let mut i = children_at_this_level.len() - 8;
         loop {
-            let siblings = &children_at_this_level.as_slice()[i..i+8];
-            let this_hash = hash_func.hash(siblings.to_vec()).unwrap();
+            starts.push(i);
+            if i == 0 {
+                break;
+            }
+            i -= 8;
+        }
+
+        let groups: Vec<Vec<FieldElement>> = starts
+            .iter()
+            .map(|&start| children_at_this_level[start..start + 8].to_vec())
+            .collect();
+        let hashes = hash_func.hash_level(groups).unwrap();
+        children_at_prev_level = Vec::with_capacity(hashes.len());
+        for (start, this_hash) in starts.into_iter().zip(hashes.into_iter()) {
             children_at_prev_level.push(this_hash.clone());
             let this_hash_bytes = this_hash.to_bytes();
             if !db.contains_key(&this_hash_bytes) {
+                let siblings = &children_at_this_level[start..start + 8];
                 let array: DbVal8ary = [
                     siblings[0].clone(),
                     siblings[1].clone(),
@@ -258,12 +336,9 @@ fn build_tree_from_bitmap<'a>(
                 ];
                 db.insert(this_hash_bytes, array);
             }
-            if i == 0 {
-                break;
-            }
-            i -= 8;
         }
     }
+    hash_func.set_level(1);
     tree.root = hash_func.hash(children_at_prev_level).unwrap();
     tree
     //Tree::new_from_precomputed(&hash_func, depth, &root).unwrap()
@@ -280,7 +355,7 @@ fn build_tree_from_bitmap<'a>(
 pub type Db = InMemoryHashDb::<DbVal8ary>;
 pub type El = FieldElement;
 
-pub type Tree<'a> = VanillaSparseMerkleTree8<'a, PoseidonHash8<'a>>;
+pub type Tree<'a, H> = VanillaSparseMerkleTree8<'a, H>;
 
 // Very fast. Profiler says average 15 nanoseconds.
 pub fn make_db() -> Db {
@@ -296,22 +371,13 @@ pub fn make_hash_params() -> PoseidonParams {
     PoseidonParams::new(width, full_b, full_e, partial_rounds).unwrap()
 }
 
-// Super fast. Profiler says average 2 nanoseconds.
-pub fn make_hash_func(hash_params: &PoseidonParams) -> PoseidonHash8 {
-    let hf = PoseidonHash8 {
-        params: &hash_params,
-        sbox: &SboxType::Quint,
-    };
-    hf
-}
-
-// Pretty slow. Profiler says average 23 milliseconds when depth = 12.
-// Time increase is linear with depth of tree:
+// Pretty slow. Profiler says average 23 milliseconds when depth = 12 with
+// the Poseidon backend. Time increase is linear with depth of tree:
 // depth = 3 -- ave time = 6 ms
 // depth = 6 -- ave time = 12 ms
 // depth = 9 -- ave time = 18 ms
 // depth = 12 -- ave time = 24 ms
-pub fn make_tree(hash_func: &PoseidonHash8, tree_depth: usize, db: &mut Db) -> i32 {
+pub fn make_tree<H: Arity8MerkleTreeHashExt>(hash_func: &H, tree_depth: usize, db: &mut Db) -> i32 {
     let _x = VanillaSparseMerkleTree8::new(hash_func, tree_depth, db).unwrap();
     0
 }
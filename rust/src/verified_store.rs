@@ -0,0 +1,220 @@
+//! Verified-streaming container for the bytes `Db::save` writes to disk.
+//!
+//! The existing integrity check in `experiment` only compares the
+//! reconstructed Poseidon root and the db length *after* a full `Db::load`,
+//! so corruption anywhere in the file isn't caught until everything has
+//! been read. This wraps the same bytes in a small BLAKE3 hash tree (1 KiB
+//! chunks, combined pairwise up to a root) stored alongside the data, so a
+//! verifying load can check each chunk as it streams in and fail on the
+//! first bad one without buffering the rest of the file.
+//!
+//! `Db` (`InMemoryHashDb` from `bulletproofs_amcl`) is a foreign type, same
+//! as in `content_store`, so this lives as free functions rather than
+//! `Db::save_verified`/`Db::load_verified` methods.
+
+use std::convert::TryInto;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub const CHUNK_SIZE: usize = 1024;
+
+pub type Digest = [u8; 32];
+
+#[derive(Debug, Clone)]
+pub enum VerifiedStoreError {
+    RootMismatch,
+    OutboardCorrupted,
+    ChunkCorrupted { chunk_index: usize, start: usize, end: usize },
+    Truncated,
+}
+
+impl fmt::Display for VerifiedStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifiedStoreError::RootMismatch => {
+                write!(f, "Stored root hash doesn't match the expected root.")
+            },
+            VerifiedStoreError::OutboardCorrupted => {
+                write!(f, "Outboard chunk hashes don't combine to the stored root.")
+            },
+            VerifiedStoreError::ChunkCorrupted { chunk_index, start, end } => {
+                write!(f, "Chunk {} (bytes {}..{}) failed verification.", chunk_index, start, end)
+            },
+            VerifiedStoreError::Truncated => {
+                write!(f, "Verified store file is truncated.")
+            }
+        }
+    }
+}
+
+impl error::Error for VerifiedStoreError {
+    fn description(&self) -> &str {
+        match self {
+            VerifiedStoreError::RootMismatch => "root mismatch",
+            VerifiedStoreError::OutboardCorrupted => "outboard corrupted",
+            VerifiedStoreError::ChunkCorrupted { .. } => "chunk corrupted",
+            VerifiedStoreError::Truncated => "truncated store",
+        }
+    }
+}
+
+fn hash_chunk(chunk: &[u8]) -> Digest {
+    *blake3::hash(chunk).as_bytes()
+}
+
+fn combine(left: &Digest, right: &Digest) -> Digest {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+/// Combine a list of leaf (chunk) hashes pairwise, up to a single root hash.
+fn build_root(leaves: &[Digest]) -> Digest {
+    if leaves.is_empty() {
+        return *blake3::hash(&[]).as_bytes();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        let mut next = Vec::with_capacity((level.len() + 1) / 2);
+        let mut i = 0;
+        while i < level.len() {
+            if i + 1 < level.len() {
+                next.push(combine(&level[i], &level[i + 1]));
+            } else {
+                next.push(level[i]);
+            }
+            i += 2;
+        }
+        level = next;
+    }
+    level[0]
+}
+
+/// Render a digest as lowercase hex, for logging.
+pub fn to_hex(digest: &Digest) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Chunk `data` into `CHUNK_SIZE` pieces, hash each with BLAKE3, and write
+/// the chunk hashes (the outboard) plus the raw data to `path`. Returns the
+/// combined root hash.
+pub fn save_verified(data: &[u8], path: &Path) -> io::Result<Digest> {
+    let leaf_hashes: Vec<Digest> = data.chunks(CHUNK_SIZE).map(hash_chunk).collect();
+    let root = build_root(&leaf_hashes);
+
+    let mut out = File::create(path)?;
+    out.write_all(&(data.len() as u64).to_le_bytes())?;
+    out.write_all(&(leaf_hashes.len() as u32).to_le_bytes())?;
+    for leaf in &leaf_hashes {
+        out.write_all(leaf)?;
+    }
+    out.write_all(&root)?;
+    out.write_all(data)?;
+    Ok(root)
+}
+
+/// Load and verify the container written by `save_verified`. The outboard
+/// is checked against `expected_root` (and against its own internal
+/// consistency) before any data is trusted; then chunks are checked one at
+/// a time as they're read, returning as soon as one fails rather than
+/// reading the rest of the file.
+pub fn load_verified(path: &Path, expected_root: Digest) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut f = File::open(path)?;
+
+    let mut header = [0u8; 12];
+    f.read_exact(&mut header).map_err(|_| VerifiedStoreError::Truncated)?;
+    let data_len = u64::from_le_bytes(header[0..8].try_into()?) as usize;
+    let chunk_count = u32::from_le_bytes(header[8..12].try_into()?) as usize;
+
+    let mut leaf_hashes = Vec::with_capacity(chunk_count);
+    for _ in 0..chunk_count {
+        let mut leaf = [0u8; 32];
+        f.read_exact(&mut leaf).map_err(|_| VerifiedStoreError::Truncated)?;
+        leaf_hashes.push(leaf);
+    }
+
+    let mut stored_root = [0u8; 32];
+    f.read_exact(&mut stored_root).map_err(|_| VerifiedStoreError::Truncated)?;
+    if stored_root != expected_root {
+        return Err(Box::new(VerifiedStoreError::RootMismatch));
+    }
+    if build_root(&leaf_hashes) != expected_root {
+        return Err(Box::new(VerifiedStoreError::OutboardCorrupted));
+    }
+
+    let mut out = Vec::with_capacity(data_len);
+    let mut remaining = data_len;
+    for (chunk_index, expected_leaf) in leaf_hashes.iter().enumerate() {
+        let this_chunk_len = CHUNK_SIZE.min(remaining);
+        let mut chunk = vec![0u8; this_chunk_len];
+        f.read_exact(&mut chunk).map_err(|_| VerifiedStoreError::Truncated)?;
+        if hash_chunk(&chunk) != *expected_leaf {
+            let start = data_len - remaining;
+            return Err(Box::new(VerifiedStoreError::ChunkCorrupted {
+                chunk_index,
+                start,
+                end: start + this_chunk_len,
+            }));
+        }
+        remaining -= this_chunk_len;
+        out.extend_from_slice(&chunk);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_clean_data() {
+        let mut data = Vec::new();
+        for i in 0..10_000u32 {
+            data.push((i % 200) as u8);
+        }
+        let dir = std::env::temp_dir();
+        let path = dir.join("verified_store_round_trip_test.bin");
+        let root = save_verified(&data, &path).unwrap();
+        let restored = load_verified(&path, root).unwrap();
+        assert_eq!(restored, data);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn detects_wrong_root() {
+        let data = vec![1u8, 2, 3, 4, 5];
+        let dir = std::env::temp_dir();
+        let path = dir.join("verified_store_wrong_root_test.bin");
+        save_verified(&data, &path).unwrap();
+        let bogus_root = [0u8; 32];
+        assert!(load_verified(&path, bogus_root).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn detects_tampered_chunk() {
+        let data = vec![9u8; CHUNK_SIZE * 3 + 17];
+        let dir = std::env::temp_dir();
+        let path = dir.join("verified_store_tampered_chunk_test.bin");
+        let root = save_verified(&data, &path).unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        // Flip a byte inside the second data chunk, well past the header
+        // and outboard.
+        let header_and_outboard_len = 12 + data.chunks(CHUNK_SIZE).count() * 32 + 32;
+        let tamper_at = header_and_outboard_len + CHUNK_SIZE + 5;
+        bytes[tamper_at] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+
+        match load_verified(&path, root) {
+            Err(e) => assert!(e.to_string().contains("Chunk")),
+            Ok(_) => panic!("expected tampering to be detected"),
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}